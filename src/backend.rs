@@ -0,0 +1,377 @@
+/*
+ * Pluggable fan control backends
+ *
+ * Fan headers are reachable either through the nct6775 sysfs interface or,
+ * on boards where the Super-I/O isn't mapped, through the ACPI/asus-wmi
+ * hwmon device. `FanBackend` abstracts the operations so the CLI dispatches
+ * identically regardless of which hardware path is present.
+ */
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const HWMON_PATH: &str = "/sys/class/hwmon";
+/*
+    Nuvoton support is essential
+    https://www.nuvoton.com/resource-files/NCT6796D_Datasheet_V0_6.pdf
+    https://docs.kernel.org/hwmon/nct6775.html
+    https://www.phoronix.com/news/Linux-6.4-nct6775-More-ASUS
+*/
+const SYSFS_CANDIDATES: &[&str] = &["nct6799", "nct6775", "nct7802", "as99127f"];
+const ASUS_WMI_CANDIDATES: &[&str] = &["asus", "asus_wmi_sensors"];
+
+pub trait FanBackend {
+    fn name(&self) -> &'static str;
+    fn list_fans(&self) -> io::Result<()>;
+    fn list_pwm(&self) -> io::Result<()>;
+    fn set_pwm(&self, pwm_index: u8, value: u8) -> io::Result<()>;
+    fn set_mode(
+        &self,
+        pwm_index: u8,
+        mode: &str,
+        target_temp: Option<u8>,
+        target_rpm: Option<u32>,
+        crit_temp: Option<u8>,
+    ) -> io::Result<()>;
+    fn set_output_mode(&self, pwm_index: u8, mode: &str) -> io::Result<()>;
+    fn read_fan_rpm(&self, pwm_index: u8) -> io::Result<u32>;
+    fn read_pwm(&self, pwm_index: u8) -> io::Result<u8>;
+}
+
+fn find_hwmon_path(candidates: &[&str]) -> io::Result<String> {
+    for entry in fs::read_dir(HWMON_PATH)? {
+        let entry = entry?;
+        let name_path = entry.path().join("name");
+        if let Ok(name) = fs::read_to_string(&name_path) {
+            if candidates.iter().any(|&s| s == name.trim()) {
+                return Ok(entry.path().to_string_lossy().into());
+            }
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("none of {:?} found under {}", candidates, HWMON_PATH),
+    ))
+}
+
+fn read_fan_rpm_at(hwmon_path: &str, pwm_index: u8) -> io::Result<u32> {
+    let fan_path = Path::new(hwmon_path).join(format!("fan{}_input", pwm_index));
+    fs::read_to_string(fan_path)?
+        .trim()
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn read_pwm_at(hwmon_path: &str, pwm_index: u8) -> io::Result<u8> {
+    let pwm_path = Path::new(hwmon_path).join(format!("pwm{}", pwm_index));
+    fs::read_to_string(pwm_path)?
+        .trim()
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Auto-detect a backend, or honor an explicit `--backend` override of
+/// "sysfs" or "asus-wmi".
+pub fn detect(preferred: Option<&str>) -> io::Result<Box<dyn FanBackend>> {
+    match preferred {
+        Some("sysfs") => Ok(Box::new(SysfsBackend::detect()?)),
+        Some("asus-wmi") => Ok(Box::new(AsusWmiBackend::detect()?)),
+        Some(other) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown backend '{}', expected 'sysfs' or 'asus-wmi'", other),
+        )),
+        None => SysfsBackend::detect()
+            .map(|b| Box::new(b) as Box<dyn FanBackend>)
+            .or_else(|_| AsusWmiBackend::detect().map(|b| Box::new(b) as Box<dyn FanBackend>)),
+    }
+}
+
+/// nct6775 (and compatible Super-I/O chips) via the sysfs hwmon interface.
+pub struct SysfsBackend {
+    hwmon_path: String,
+}
+
+impl SysfsBackend {
+    pub fn detect() -> io::Result<Self> {
+        Ok(SysfsBackend {
+            hwmon_path: find_hwmon_path(SYSFS_CANDIDATES)?,
+        })
+    }
+
+    /// Maps the symbolic pwm_enable modes documented for nct6775 to their
+    /// sysfs integer values: 0=full-speed, 1=manual, 2=thermal cruise,
+    /// 3=fan speed cruise, 4=Smart Fan III, 5=Smart Fan IV.
+    fn mode_name_to_value(mode: &str) -> io::Result<&'static str> {
+        match mode {
+            "full" => Ok("0"),
+            "manual" => Ok("1"),
+            "auto" | "thermal-cruise" => Ok("2"),
+            "speed-cruise" => Ok("3"),
+            "smartfan3" => Ok("4"),
+            "smartfan4" => Ok("5"),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "mode must be one of: full, manual, auto, thermal-cruise, speed-cruise, smartfan3, smartfan4",
+            )),
+        }
+    }
+}
+
+impl FanBackend for SysfsBackend {
+    fn name(&self) -> &'static str {
+        "sysfs"
+    }
+
+    fn list_fans(&self) -> io::Result<()> {
+        for i in 1..=7 {
+            let fan_path = Path::new(&self.hwmon_path).join(format!("fan{}_input", i));
+            if fan_path.exists() {
+                let val = fs::read_to_string(fan_path)?.trim().to_string();
+                println!("Fan{}: {} RPM", i, val);
+            }
+        }
+        Ok(())
+    }
+
+    fn list_pwm(&self) -> io::Result<()> {
+        for i in 1..=7 {
+            let pwm_path = Path::new(&self.hwmon_path).join(format!("pwm{}", i));
+            let enable_path = Path::new(&self.hwmon_path).join(format!("pwm{}_enable", i));
+            let max_path = Path::new(&self.hwmon_path).join(format!("pwm{}_max", i));
+
+            if pwm_path.exists() && enable_path.exists() {
+                let val: u8 = fs::read_to_string(&pwm_path)?.trim().parse().unwrap_or(0);
+                let mode = match fs::read_to_string(&enable_path)?.trim() {
+                    "0" => "full",
+                    "1" => "manual",
+                    "2" => "thermal-cruise",
+                    "3" => "speed-cruise",
+                    "4" => "smartfan3",
+                    "5" => "smartfan4",
+                    _ => "unknown",
+                };
+                let max_val: u8 = if max_path.exists() {
+                    fs::read_to_string(&max_path)?.trim().parse().unwrap_or(255)
+                } else {
+                    255
+                };
+                let percent = (val as f32 / max_val as f32) * 100.0;
+
+                // pwmN_mode isn't exported by every chip/channel; report
+                // "unsupported" rather than erroring, same as the max_path fallback above.
+                let mode_path = Path::new(&self.hwmon_path).join(format!("pwm{}_mode", i));
+                let output_mode = if mode_path.exists() {
+                    match fs::read_to_string(&mode_path)?.trim() {
+                        "0" => "DC",
+                        "1" => "PWM",
+                        _ => "unknown",
+                    }
+                } else {
+                    "unsupported"
+                };
+
+                println!(
+                    "PWM{}: value={}, ~{:.1}%, mode={}, output={}",
+                    i, val, percent, mode, output_mode
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn set_pwm(&self, pwm_index: u8, value: u8) -> io::Result<()> {
+        let enable_path = Path::new(&self.hwmon_path).join(format!("pwm{}_enable", pwm_index));
+        let pwm_path = Path::new(&self.hwmon_path).join(format!("pwm{}", pwm_index));
+        let max_path = Path::new(&self.hwmon_path).join(format!("pwm{}_max", pwm_index));
+
+        fs::write(&enable_path, b"1")?;
+        fs::write(&pwm_path, format!("{}", value))?;
+
+        let max_val: u8 = if max_path.exists() {
+            fs::read_to_string(&max_path)?.trim().parse().unwrap_or(255)
+        } else {
+            255
+        };
+
+        let percent = (value as f32 / max_val as f32) * 100.0;
+        println!("Set pwm{} to {} (~{:.1}%)", pwm_index, value, percent);
+        Ok(())
+    }
+
+    fn set_mode(
+        &self,
+        pwm_index: u8,
+        mode: &str,
+        target_temp: Option<u8>,
+        target_rpm: Option<u32>,
+        crit_temp: Option<u8>,
+    ) -> io::Result<()> {
+        let enable_path = Path::new(&self.hwmon_path).join(format!("pwm{}_enable", pwm_index));
+        let mode_val = Self::mode_name_to_value(mode)?;
+        fs::write(enable_path, mode_val)?;
+        println!("Set pwm{} mode to {}", pwm_index, mode);
+
+        // Companion attributes for the cruise modes: the chip holds these
+        // targets in hardware once set, no daemon polling required.
+        if let Some(temp) = target_temp {
+            let target_temp_path =
+                Path::new(&self.hwmon_path).join(format!("pwm{}_target_temp", pwm_index));
+            fs::write(&target_temp_path, format!("{}", temp))?;
+            println!("Set pwm{}_target_temp to {} °C", pwm_index, temp);
+        }
+        if let Some(rpm) = target_rpm {
+            let fan_target_path =
+                Path::new(&self.hwmon_path).join(format!("fan{}_target", pwm_index));
+            fs::write(&fan_target_path, format!("{}", rpm))?;
+            println!("Set fan{}_target to {} RPM", pwm_index, rpm);
+        }
+        if let Some(temp) = crit_temp {
+            let crit_temp_path =
+                Path::new(&self.hwmon_path).join(format!("pwm{}_crit_temp", pwm_index));
+            fs::write(&crit_temp_path, format!("{}", temp))?;
+            println!("Set pwm{}_crit_temp to {} °C", pwm_index, temp);
+        }
+
+        Ok(())
+    }
+
+    fn set_output_mode(&self, pwm_index: u8, mode: &str) -> io::Result<()> {
+        let mode_path = Path::new(&self.hwmon_path).join(format!("pwm{}_mode", pwm_index));
+        let mode_val = match mode {
+            "dc" => "0",
+            "pwm" => "1",
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "mode must be 'dc' or 'pwm'",
+                ));
+            }
+        };
+
+        if !mode_path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("pwm{}_mode is not exported by this chip/channel", pwm_index),
+            ));
+        }
+
+        fs::write(mode_path, mode_val)?;
+        println!("Set pwm{} output mode to {}", pwm_index, mode);
+        Ok(())
+    }
+
+    fn read_fan_rpm(&self, pwm_index: u8) -> io::Result<u32> {
+        read_fan_rpm_at(&self.hwmon_path, pwm_index)
+    }
+
+    fn read_pwm(&self, pwm_index: u8) -> io::Result<u8> {
+        read_pwm_at(&self.hwmon_path, pwm_index)
+    }
+}
+
+/// ASUS Strix boards where fan headers are exposed through the ACPI/asus-wmi
+/// hwmon device instead of a mapped Super-I/O chip. Only manual PWM control
+/// is available here, so the cruise modes and pwmN_mode are reported as
+/// unsupported rather than attempted.
+pub struct AsusWmiBackend {
+    hwmon_path: String,
+}
+
+impl AsusWmiBackend {
+    pub fn detect() -> io::Result<Self> {
+        Ok(AsusWmiBackend {
+            hwmon_path: find_hwmon_path(ASUS_WMI_CANDIDATES)?,
+        })
+    }
+}
+
+impl FanBackend for AsusWmiBackend {
+    fn name(&self) -> &'static str {
+        "asus-wmi"
+    }
+
+    fn list_fans(&self) -> io::Result<()> {
+        for i in 1..=7 {
+            let fan_path = Path::new(&self.hwmon_path).join(format!("fan{}_input", i));
+            if fan_path.exists() {
+                let val = fs::read_to_string(fan_path)?.trim().to_string();
+                println!("Fan{}: {} RPM", i, val);
+            }
+        }
+        Ok(())
+    }
+
+    fn list_pwm(&self) -> io::Result<()> {
+        for i in 1..=7 {
+            let pwm_path = Path::new(&self.hwmon_path).join(format!("pwm{}", i));
+            let enable_path = Path::new(&self.hwmon_path).join(format!("pwm{}_enable", i));
+
+            if pwm_path.exists() && enable_path.exists() {
+                let val: u8 = fs::read_to_string(&pwm_path)?.trim().parse().unwrap_or(0);
+                let mode = match fs::read_to_string(&enable_path)?.trim() {
+                    "1" => "manual",
+                    "2" => "auto",
+                    _ => "unknown",
+                };
+                let percent = (val as f32 / 255.0) * 100.0;
+                println!(
+                    "PWM{}: value={}, ~{:.1}%, mode={}, output=unsupported",
+                    i, val, percent, mode
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn set_pwm(&self, pwm_index: u8, value: u8) -> io::Result<()> {
+        let enable_path = Path::new(&self.hwmon_path).join(format!("pwm{}_enable", pwm_index));
+        let pwm_path = Path::new(&self.hwmon_path).join(format!("pwm{}", pwm_index));
+
+        fs::write(&enable_path, b"1")?;
+        fs::write(&pwm_path, format!("{}", value))?;
+
+        let percent = (value as f32 / 255.0) * 100.0;
+        println!("Set pwm{} to {} (~{:.1}%)", pwm_index, value, percent);
+        Ok(())
+    }
+
+    fn set_mode(
+        &self,
+        pwm_index: u8,
+        mode: &str,
+        _target_temp: Option<u8>,
+        _target_rpm: Option<u32>,
+        _crit_temp: Option<u8>,
+    ) -> io::Result<()> {
+        let enable_path = Path::new(&self.hwmon_path).join(format!("pwm{}_enable", pwm_index));
+        let mode_val = match mode {
+            "manual" => "1",
+            "auto" => "2",
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "asus-wmi backend only supports 'manual' and 'auto'",
+                ));
+            }
+        };
+        fs::write(enable_path, mode_val)?;
+        println!("Set pwm{} mode to {}", pwm_index, mode);
+        Ok(())
+    }
+
+    fn set_output_mode(&self, _pwm_index: u8, _mode: &str) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "asus-wmi backend does not expose pwmN_mode",
+        ))
+    }
+
+    fn read_fan_rpm(&self, pwm_index: u8) -> io::Result<u32> {
+        read_fan_rpm_at(&self.hwmon_path, pwm_index)
+    }
+
+    fn read_pwm(&self, pwm_index: u8) -> io::Result<u8> {
+        read_pwm_at(&self.hwmon_path, pwm_index)
+    }
+}
@@ -0,0 +1,606 @@
+/*
+ * Config subsystem for fancontrol.toml
+ *
+ * Loads per-channel fan curves (temp_c -> pwm breakpoints), poll interval,
+ * hysteresis, and PWM clamps so behavior can be tuned without recompiling.
+ * Each channel names its temperature input via the `sensors` module, so a
+ * curve can track a CPU, VRM, or coolant sensor independently.
+ */
+
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Search order for the config file: current directory first, then the
+/// system-wide location.
+const CONFIG_PATHS: &[&str] = &["./fancontrol.toml", "/etc/fancontrol.toml"];
+
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    #[serde(rename = "channel", default)]
+    pub channels: Vec<ChannelConfig>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ChannelConfig {
+    pub pwm_index: u8,
+    /// Temperature source name as "chip/label", e.g. "k10temp/Tctl" or
+    /// "nct6799/AUXTIN1", or the synthetic "max" for the hottest of all
+    /// enumerated sensors.
+    #[serde(default = "default_source")]
+    pub source: String,
+    #[serde(default = "default_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+    #[serde(default = "default_hysteresis_c")]
+    pub hysteresis_c: f32,
+    #[serde(default = "default_min_pwm")]
+    pub min_pwm: u8,
+    #[serde(default = "default_max_pwm")]
+    pub max_pwm: u8,
+    /// Empty when the channel is driven purely by `cooling_states` instead
+    /// of continuous interpolation.
+    #[serde(default)]
+    pub curve: Vec<CurvePoint>,
+    /// When present, the daemon runs closed-loop: the curve above still
+    /// sets a base PWM, but a PI controller trims it to hold the RPM target
+    /// derived from `rpm_curve`.
+    #[serde(default)]
+    pub closed_loop: Option<ClosedLoopConfig>,
+    /// When non-empty, the daemon snaps to these named cooling states
+    /// instead of continuously interpolating `curve`. States are ordered
+    /// quietest-first; see `CoolingStateMachine`.
+    #[serde(default)]
+    pub cooling_states: Vec<CoolingState>,
+}
+
+/// One entry in a discrete cooling-level table, modeled after the kernel's
+/// pwm-fan cooling-device states: an ordered array of PWM levels with
+/// built-in hysteresis between the entry and exit thresholds.
+#[derive(Debug, Deserialize, Clone)]
+pub struct CoolingState {
+    pub name: String,
+    pub pwm: u8,
+    /// Temperature at or above which the daemon advances into this state.
+    pub entry_temp_c: f32,
+    /// Temperature below which the daemon drops back out of this state.
+    pub exit_temp_c: f32,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ClosedLoopConfig {
+    #[serde(default = "default_kp")]
+    pub kp: f32,
+    #[serde(default = "default_ki")]
+    pub ki: f32,
+    /// Anti-windup bound on the integral accumulator.
+    #[serde(default = "default_integral_bound")]
+    pub integral_bound: f32,
+    /// Consecutive zero-RPM samples (with a nonzero target) before the fan
+    /// is considered stalled and kicked to full PWM.
+    #[serde(default = "default_stall_samples")]
+    pub stall_samples: u32,
+    pub rpm_curve: Vec<RpmCurvePoint>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct RpmCurvePoint {
+    pub temp_c: f32,
+    pub target_rpm: u32,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct CurvePoint {
+    pub temp_c: f32,
+    pub pwm: u8,
+}
+
+fn default_source() -> String {
+    crate::sensors::DEFAULT_CPU_SOURCE.to_string()
+}
+
+fn default_poll_interval_secs() -> u64 {
+    5
+}
+
+fn default_hysteresis_c() -> f32 {
+    3.0
+}
+
+fn default_min_pwm() -> u8 {
+    0
+}
+
+fn default_max_pwm() -> u8 {
+    255
+}
+
+fn default_kp() -> f32 {
+    0.1
+}
+
+fn default_ki() -> f32 {
+    0.02
+}
+
+fn default_integral_bound() -> f32 {
+    2000.0
+}
+
+fn default_stall_samples() -> u32 {
+    3
+}
+
+impl Config {
+    /// Load from `./fancontrol.toml` or `/etc/fancontrol.toml`, in that order.
+    pub fn load() -> io::Result<Config> {
+        for path in CONFIG_PATHS {
+            if Path::new(path).exists() {
+                return Config::load_from(path);
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "no config file found (looked in {})",
+                CONFIG_PATHS.join(", ")
+            ),
+        ))
+    }
+
+    pub fn load_from(path: &str) -> io::Result<Config> {
+        let raw = fs::read_to_string(path)?;
+        let config: Config =
+            toml::from_str(&raw).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        for channel in &config.channels {
+            if channel.curve.is_empty() && channel.cooling_states.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "pwm{} channel has neither `curve` nor `cooling_states` configured",
+                        channel.pwm_index
+                    ),
+                ));
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Linear interpolation between the curve points adjacent to `temp_c`,
+/// clamped below the first point and above the last. Result is rounded to
+/// the nearest u8.
+pub fn interpolate_pwm(curve: &[CurvePoint], temp_c: f32) -> u8 {
+    if curve.is_empty() {
+        return 0;
+    }
+    if temp_c <= curve[0].temp_c {
+        return curve[0].pwm;
+    }
+    let last = curve[curve.len() - 1];
+    if temp_c >= last.temp_c {
+        return last.pwm;
+    }
+    for pair in curve.windows(2) {
+        let (p0, p1) = (pair[0], pair[1]);
+        if temp_c >= p0.temp_c && temp_c <= p1.temp_c {
+            let pwm = p0.pwm as f32
+                + (temp_c - p0.temp_c) * (p1.pwm as f32 - p0.pwm as f32) / (p1.temp_c - p0.temp_c);
+            return pwm.round() as u8;
+        }
+    }
+    last.pwm
+}
+
+/// Same clamped linear interpolation as `interpolate_pwm`, but over an
+/// RPM target curve.
+pub fn interpolate_target_rpm(curve: &[RpmCurvePoint], temp_c: f32) -> u32 {
+    if curve.is_empty() {
+        return 0;
+    }
+    if temp_c <= curve[0].temp_c {
+        return curve[0].target_rpm;
+    }
+    let last = curve[curve.len() - 1];
+    if temp_c >= last.temp_c {
+        return last.target_rpm;
+    }
+    for pair in curve.windows(2) {
+        let (p0, p1) = (pair[0], pair[1]);
+        if temp_c >= p0.temp_c && temp_c <= p1.temp_c {
+            let rpm = p0.target_rpm as f32
+                + (temp_c - p0.temp_c) * (p1.target_rpm as f32 - p0.target_rpm as f32)
+                    / (p1.temp_c - p0.temp_c);
+            return rpm.round() as u32;
+        }
+    }
+    last.target_rpm
+}
+
+/// PI controller that trims a base PWM to hold a target RPM, compensating
+/// for fan aging, voltage sag, or restricted airflow.
+pub struct PiController {
+    integral: f32,
+    stall_count: u32,
+}
+
+/// Outcome of one `PiController::step`.
+pub struct PiOutput {
+    pub pwm: u8,
+    pub stalled: bool,
+}
+
+/// The PWM range `PiController::step` is allowed to land in, taken from the
+/// same `ChannelConfig::min_pwm`/`max_pwm` clamp the open-loop curve uses.
+#[derive(Clone, Copy)]
+pub struct PwmBounds {
+    pub min_pwm: u8,
+    pub max_pwm: u8,
+}
+
+impl Default for PiController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PiController {
+    pub fn new() -> Self {
+        PiController {
+            integral: 0.0,
+            stall_count: 0,
+        }
+    }
+
+    /// `dt_secs` is the time since the last step. `base_pwm` is the
+    /// open-loop PWM from the temperature curve, used as the PI controller's
+    /// starting point each tick.
+    pub fn step(
+        &mut self,
+        closed_loop: &ClosedLoopConfig,
+        target_rpm: u32,
+        measured_rpm: u32,
+        base_pwm: u8,
+        bounds: PwmBounds,
+        dt_secs: f32,
+    ) -> PiOutput {
+        if target_rpm > 0 && measured_rpm == 0 {
+            self.stall_count += 1;
+        } else {
+            self.stall_count = 0;
+        }
+
+        if self.stall_count >= closed_loop.stall_samples && target_rpm > 0 {
+            return PiOutput {
+                pwm: bounds.max_pwm,
+                stalled: true,
+            };
+        }
+
+        let error = target_rpm as f32 - measured_rpm as f32;
+        self.integral = (self.integral + error * dt_secs)
+            .clamp(-closed_loop.integral_bound, closed_loop.integral_bound);
+        let pwm = base_pwm as f32 + closed_loop.kp * error + closed_loop.ki * self.integral;
+        PiOutput {
+            pwm: pwm
+                .round()
+                .clamp(bounds.min_pwm as f32, bounds.max_pwm as f32) as u8,
+            stalled: false,
+        }
+    }
+}
+
+/// Tracks the last decision point per channel so the daemon only recomputes
+/// the target PWM once the temperature has moved past the hysteresis band.
+pub struct HysteresisState {
+    pub last_decision_temp: Option<f32>,
+    pub last_pwm: u8,
+}
+
+impl Default for HysteresisState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HysteresisState {
+    pub fn new() -> Self {
+        HysteresisState {
+            last_decision_temp: None,
+            last_pwm: 0,
+        }
+    }
+
+    /// Returns the PWM to apply this tick, recomputing from the curve only
+    /// when `temp_c` has moved more than `hysteresis_c` from the last
+    /// decision point.
+    pub fn update(&mut self, channel: &ChannelConfig, temp_c: f32) -> u8 {
+        let should_recompute = match self.last_decision_temp {
+            None => true,
+            Some(last_temp) => (temp_c - last_temp).abs() > channel.hysteresis_c,
+        };
+
+        if should_recompute {
+            let pwm = interpolate_pwm(&channel.curve, temp_c).clamp(channel.min_pwm, channel.max_pwm);
+            self.last_decision_temp = Some(temp_c);
+            self.last_pwm = pwm;
+        }
+
+        self.last_pwm
+    }
+}
+
+/// Advances through an ordered `CoolingState` table, stepping up only past
+/// the next state's `entry_temp_c` and back down only below the current
+/// state's `exit_temp_c`, giving hysteresis "for free" between bands.
+pub struct CoolingStateMachine {
+    pub current_index: usize,
+}
+
+impl Default for CoolingStateMachine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CoolingStateMachine {
+    pub fn new() -> Self {
+        CoolingStateMachine { current_index: 0 }
+    }
+
+    pub fn update(&mut self, states: &[CoolingState], temp_c: f32) -> u8 {
+        if states.is_empty() {
+            return 0;
+        }
+        while self.current_index + 1 < states.len()
+            && temp_c >= states[self.current_index + 1].entry_temp_c
+        {
+            self.current_index += 1;
+        }
+        while self.current_index > 0 && temp_c < states[self.current_index].exit_temp_c {
+            self.current_index -= 1;
+        }
+        states[self.current_index].pwm
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_curve() -> Vec<CurvePoint> {
+        vec![
+            CurvePoint {
+                temp_c: 40.0,
+                pwm: 80,
+            },
+            CurvePoint {
+                temp_c: 60.0,
+                pwm: 180,
+            },
+            CurvePoint {
+                temp_c: 80.0,
+                pwm: 255,
+            },
+        ]
+    }
+
+    fn sample_channel(curve: Vec<CurvePoint>, hysteresis_c: f32) -> ChannelConfig {
+        ChannelConfig {
+            pwm_index: 1,
+            source: default_source(),
+            poll_interval_secs: default_poll_interval_secs(),
+            hysteresis_c,
+            min_pwm: default_min_pwm(),
+            max_pwm: default_max_pwm(),
+            curve,
+            closed_loop: None,
+            cooling_states: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn interpolate_pwm_clamps_below_first_point() {
+        assert_eq!(interpolate_pwm(&sample_curve(), 10.0), 80);
+    }
+
+    #[test]
+    fn interpolate_pwm_clamps_above_last_point() {
+        assert_eq!(interpolate_pwm(&sample_curve(), 200.0), 255);
+    }
+
+    #[test]
+    fn interpolate_pwm_hits_breakpoints_exactly() {
+        assert_eq!(interpolate_pwm(&sample_curve(), 40.0), 80);
+        assert_eq!(interpolate_pwm(&sample_curve(), 60.0), 180);
+    }
+
+    #[test]
+    fn interpolate_pwm_interpolates_linearly_between_points() {
+        // Midpoint between (40, 80) and (60, 180) should round to 130.
+        assert_eq!(interpolate_pwm(&sample_curve(), 50.0), 130);
+    }
+
+    #[test]
+    fn interpolate_pwm_empty_curve_returns_zero() {
+        assert_eq!(interpolate_pwm(&[], 50.0), 0);
+    }
+
+    #[test]
+    fn interpolate_target_rpm_clamps_and_interpolates() {
+        let curve = vec![
+            RpmCurvePoint {
+                temp_c: 30.0,
+                target_rpm: 600,
+            },
+            RpmCurvePoint {
+                temp_c: 50.0,
+                target_rpm: 1400,
+            },
+        ];
+        assert_eq!(interpolate_target_rpm(&curve, 10.0), 600);
+        assert_eq!(interpolate_target_rpm(&curve, 100.0), 1400);
+        assert_eq!(interpolate_target_rpm(&curve, 40.0), 1000);
+    }
+
+    #[test]
+    fn hysteresis_state_recomputes_on_first_call() {
+        let channel = sample_channel(sample_curve(), 3.0);
+        let mut state = HysteresisState::new();
+        assert_eq!(state.update(&channel, 50.0), 130);
+    }
+
+    #[test]
+    fn hysteresis_state_holds_pwm_within_band() {
+        let channel = sample_channel(sample_curve(), 3.0);
+        let mut state = HysteresisState::new();
+        state.update(&channel, 50.0);
+        // Within the 3 degree band, the stale PWM should be held.
+        assert_eq!(state.update(&channel, 52.0), 130);
+    }
+
+    #[test]
+    fn hysteresis_state_recomputes_once_band_exceeded() {
+        let channel = sample_channel(sample_curve(), 3.0);
+        let mut state = HysteresisState::new();
+        state.update(&channel, 50.0);
+        assert_eq!(state.update(&channel, 55.0), 155);
+    }
+
+    #[test]
+    fn hysteresis_state_clamps_to_channel_bounds() {
+        let mut channel = sample_channel(sample_curve(), 3.0);
+        channel.min_pwm = 100;
+        channel.max_pwm = 150;
+        let mut state = HysteresisState::new();
+        // Curve alone would give 80, but the channel clamps to [100, 150].
+        assert_eq!(state.update(&channel, 10.0), 100);
+    }
+
+    fn sample_closed_loop() -> ClosedLoopConfig {
+        ClosedLoopConfig {
+            kp: 0.1,
+            ki: 0.0,
+            integral_bound: 2000.0,
+            stall_samples: 2,
+            rpm_curve: vec![RpmCurvePoint {
+                temp_c: 50.0,
+                target_rpm: 1000,
+            }],
+        }
+    }
+
+    fn full_range() -> PwmBounds {
+        PwmBounds {
+            min_pwm: 0,
+            max_pwm: 255,
+        }
+    }
+
+    #[test]
+    fn pi_controller_trims_base_pwm_toward_target() {
+        let closed_loop = sample_closed_loop();
+        let mut controller = PiController::new();
+        let output = controller.step(&closed_loop, 1000, 800, 128, full_range(), 1.0);
+        assert!(!output.stalled);
+        // error = 200, kp = 0.1 -> +20 over the base PWM.
+        assert_eq!(output.pwm, 148);
+    }
+
+    #[test]
+    fn pi_controller_clamps_to_min_max_pwm() {
+        let closed_loop = sample_closed_loop();
+        let mut controller = PiController::new();
+        let output = controller.step(&closed_loop, 1000, 0, 250, full_range(), 1.0);
+        assert_eq!(output.pwm, 255);
+    }
+
+    #[test]
+    fn pi_controller_detects_stall_after_consecutive_zero_rpm_samples() {
+        let closed_loop = sample_closed_loop();
+        let mut controller = PiController::new();
+        assert!(!controller.step(&closed_loop, 1000, 0, 128, full_range(), 1.0).stalled);
+        let output = controller.step(&closed_loop, 1000, 0, 128, full_range(), 1.0);
+        assert!(output.stalled);
+        assert_eq!(output.pwm, 255);
+    }
+
+    #[test]
+    fn pi_controller_clamps_stall_kick_to_max_pwm() {
+        let closed_loop = sample_closed_loop();
+        let mut controller = PiController::new();
+        let bounds = PwmBounds {
+            min_pwm: 0,
+            max_pwm: 180,
+        };
+        controller.step(&closed_loop, 1000, 0, 128, bounds, 1.0);
+        let output = controller.step(&closed_loop, 1000, 0, 128, bounds, 1.0);
+        assert!(output.stalled);
+        assert_eq!(output.pwm, 180);
+    }
+
+    #[test]
+    fn pi_controller_resets_stall_count_once_fan_spins_again() {
+        let closed_loop = sample_closed_loop();
+        let mut controller = PiController::new();
+        controller.step(&closed_loop, 1000, 0, 128, full_range(), 1.0);
+        controller.step(&closed_loop, 1000, 900, 128, full_range(), 1.0);
+        let output = controller.step(&closed_loop, 1000, 0, 128, full_range(), 1.0);
+        assert!(!output.stalled);
+    }
+
+    fn sample_cooling_states() -> Vec<CoolingState> {
+        vec![
+            CoolingState {
+                name: "silent".to_string(),
+                pwm: 60,
+                entry_temp_c: 0.0,
+                exit_temp_c: 0.0,
+            },
+            CoolingState {
+                name: "balanced".to_string(),
+                pwm: 140,
+                entry_temp_c: 50.0,
+                exit_temp_c: 45.0,
+            },
+            CoolingState {
+                name: "turbo".to_string(),
+                pwm: 255,
+                entry_temp_c: 70.0,
+                exit_temp_c: 65.0,
+            },
+        ]
+    }
+
+    #[test]
+    fn cooling_state_machine_starts_quietest() {
+        let mut machine = CoolingStateMachine::new();
+        assert_eq!(machine.update(&sample_cooling_states(), 30.0), 60);
+    }
+
+    #[test]
+    fn cooling_state_machine_advances_past_entry_threshold() {
+        let states = sample_cooling_states();
+        let mut machine = CoolingStateMachine::new();
+        assert_eq!(machine.update(&states, 55.0), 140);
+        assert_eq!(machine.update(&states, 75.0), 255);
+    }
+
+    #[test]
+    fn cooling_state_machine_holds_state_until_exit_threshold() {
+        let states = sample_cooling_states();
+        let mut machine = CoolingStateMachine::new();
+        machine.update(&states, 55.0);
+        // Dropping below entry (50) but still above exit (45) should hold.
+        assert_eq!(machine.update(&states, 48.0), 140);
+        assert_eq!(machine.update(&states, 40.0), 60);
+    }
+
+    #[test]
+    fn cooling_state_machine_empty_table_returns_zero() {
+        let mut machine = CoolingStateMachine::new();
+        assert_eq!(machine.update(&[], 50.0), 0);
+    }
+}
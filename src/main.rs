@@ -23,27 +23,28 @@
  * Licensed under the MIT License. See LICENSE for details.
 */
 
+mod backend;
+mod config;
+mod sensors;
+
+use backend::FanBackend;
 use clap::{Parser, Subcommand};
-use std::fs;
+use config::{ChannelConfig, Config, CoolingStateMachine, HysteresisState, PiController};
+use std::collections::HashMap;
 use std::io;
 use std::path::Path;
 use std::thread;
-use std::time::Duration;
-
-const HWMON_PATH: &str = "/sys/class/hwmon";
-const K10TEMP_SENSOR_NAME: &str = "k10temp";
-/*
-    Nuvoton support is essential
-    https://www.nuvoton.com/resource-files/NCT6796D_Datasheet_V0_6.pdf
-    https://docs.kernel.org/hwmon/nct6775.html
-    https://www.phoronix.com/news/Linux-6.4-nct6775-More-ASUS
-*/
-const SENSOR_CANDIDATES: &[&str] = &["nct6799", "nct6775", "nct7802", "as99127f"];
+use std::time::{Duration, Instant};
 
 #[derive(Parser)]
 #[command(name = "fancontrol")]
 #[command(about = "Rust CLI utility for temperature and fan control")]
 struct Cli {
+    /// Override backend auto-detection: "sysfs" (nct6775 via sysfs) or
+    /// "asus-wmi" (ACPI/asus-wmi hwmon device)
+    #[arg(long)]
+    backend: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -59,12 +60,41 @@ enum Commands {
     },
     SetMode {
         pwm_index: u8,
-        // Can be set to manual or auto, auto is the default BIOS settings
+        /// manual, auto, full, thermal-cruise, speed-cruise, smartfan3, smartfan4
+        /// (auto is an alias for thermal-cruise, the default BIOS setting)
+        mode: String,
+        /// Target temperature in °C for thermal-cruise (writes pwmN_target_temp)
+        #[arg(long)]
+        target_temp: Option<u8>,
+        /// Target fan RPM for speed-cruise (writes fanN_target)
+        #[arg(long)]
+        target_rpm: Option<u32>,
+        /// Critical temperature in °C at which the chip forces full speed (writes pwmN_crit_temp)
+        #[arg(long)]
+        crit_temp: Option<u8>,
+    },
+    SetOutputMode {
+        pwm_index: u8,
+        /// dc or pwm
         mode: String,
     },
     Daemon {
-        #[arg(short, long, default_value_t = 1)]
+        /// Path to fancontrol.toml. Defaults to ./fancontrol.toml, falling
+        /// back to /etc/fancontrol.toml.
+        #[arg(short, long)]
+        config: Option<String>,
+    },
+    SetCoolingState {
+        pwm_index: u8,
+        /// Index into the channel's configured cooling_states table
+        index: usize,
+        #[arg(short, long)]
+        config: Option<String>,
+    },
+    ListCoolingStates {
         pwm_index: u8,
+        #[arg(short, long)]
+        config: Option<String>,
     },
 }
 
@@ -72,172 +102,228 @@ fn check_module_loaded() -> bool {
     Path::new("/sys/module/nct6775").exists()
 }
 
-fn find_hwmon_path(sensor_name: &str) -> io::Result<String> {
-    for entry in fs::read_dir(HWMON_PATH)? {
-        // need to replace with match, this is not production
-        let entry = entry?;
-        let name_path = entry.path().join("name");
-        if let Ok(name) = fs::read_to_string(&name_path) {
-            if name.trim() == sensor_name {
-                return Ok(entry.path().to_string_lossy().into());
-            }
-        }
+/// Auto-detects the control backend honoring `--backend`, printing the
+/// nct6775 module warning only when that's actually the backend in play.
+fn detect_backend(preferred: Option<&str>) -> io::Result<Box<dyn FanBackend>> {
+    let fan_backend = backend::detect(preferred)?;
+    if fan_backend.name() == "sysfs" && !check_module_loaded() {
+        println!("Warning: 'nct6775' kernel module is not loaded.");
+        println!("Run: sudo modprobe nct6775");
     }
-    Err(io::Error::new(
-        io::ErrorKind::NotFound,
-        format!("{} sensor not found", sensor_name),
-    ))
+    Ok(fan_backend)
 }
 
-fn find_hwmon_path_dynamic() -> io::Result<String> {
-    for entry in fs::read_dir(HWMON_PATH)? {
-        let entry = entry?;
-        let name_path = entry.path().join("name");
-        if let Ok(name) = fs::read_to_string(&name_path) {
-            if SENSOR_CANDIDATES.iter().any(|&s| s == name.trim()) {
-                return Ok(entry.path().to_string_lossy().into());
-            }
-        }
+fn run_daemon(config_path: Option<&str>, fan_backend: &dyn FanBackend) -> io::Result<()> {
+    let config = match config_path {
+        Some(path) => Config::load_from(path)?,
+        None => Config::load()?,
+    };
+
+    if config.channels.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "config has no [[channel]] entries",
+        ));
     }
-    Err(io::Error::new(
-        io::ErrorKind::NotFound,
-        "No supported sensor found",
-    ))
-}
 
-fn read_cpu_temperature() -> io::Result<f32> {
-    let path = find_hwmon_path(K10TEMP_SENSOR_NAME)?;
-    let temp_path = Path::new(&path).join("temp1_input");
-    let raw = fs::read_to_string(temp_path)?;
-    let milli_degrees: i32 = raw
-        .trim()
-        .parse()
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-    Ok(milli_degrees as f32 / 1000.0)
-}
+    println!(
+        "Starting fan control daemon on {} channel(s)",
+        config.channels.len()
+    );
 
-fn list_fans() -> io::Result<()> {
-    let path = find_hwmon_path_dynamic()?;
-    for i in 1..=7 {
-        let fan_path = Path::new(&path).join(format!("fan{}_input", i));
-        if fan_path.exists() {
-            let val = fs::read_to_string(fan_path)?.trim().to_string();
-            println!("Fan{}: {} RPM", i, val);
-        }
-    }
-    Ok(())
-}
+    let mut states: HashMap<u8, HysteresisState> = HashMap::new();
+    let mut cooling_machines: HashMap<u8, CoolingStateMachine> = HashMap::new();
+    let mut controllers: HashMap<u8, PiController> = HashMap::new();
+    let mut last_poll: HashMap<u8, Instant> = HashMap::new();
+    let tick = config
+        .channels
+        .iter()
+        .map(|c| c.poll_interval_secs)
+        .min()
+        .unwrap_or(5)
+        .max(1);
 
-fn list_pwm() -> io::Result<()> {
-    let path = find_hwmon_path_dynamic()?;
-    for i in 1..=7 {
-        let pwm_path = Path::new(&path).join(format!("pwm{}", i));
-        let enable_path = Path::new(&path).join(format!("pwm{}_enable", i));
-        let max_path = Path::new(&path).join(format!("pwm{}_max", i));
-
-        if pwm_path.exists() && enable_path.exists() {
-            let val: u8 = fs::read_to_string(&pwm_path)?.trim().parse().unwrap_or(0);
-            let mode = match fs::read_to_string(&enable_path)?.trim() {
-                "1" => "manual",
-                "2" => "auto",
-                _ => "unknown",
+    loop {
+        let now = Instant::now();
+        let readings = sensors::enumerate_sensors()?;
+        for channel in &config.channels {
+            let dt_secs = match last_poll.get(&channel.pwm_index) {
+                Some(last) => now.duration_since(*last).as_secs_f32(),
+                None => channel.poll_interval_secs as f32,
             };
-            let max_val: u8 = if max_path.exists() {
-                fs::read_to_string(&max_path)?.trim().parse().unwrap_or(255)
+            if dt_secs < channel.poll_interval_secs as f32 {
+                continue;
+            }
+
+            let temp = sensors::resolve_source(&channel.source, &readings)?;
+            let base_pwm = if !channel.cooling_states.is_empty() {
+                let machine = cooling_machines.entry(channel.pwm_index).or_default();
+                machine.update(&channel.cooling_states, temp)
             } else {
-                255
+                let state = states.entry(channel.pwm_index).or_default();
+                state.update(channel, temp)
             };
-            let percent = (val as f32 / max_val as f32) * 100.0;
-            println!("PWM{}: value={}, ~{:.1}%, mode={}", i, val, percent, mode);
+
+            let pwm = if let Some(closed_loop) = &channel.closed_loop {
+                let target_rpm = config::interpolate_target_rpm(&closed_loop.rpm_curve, temp);
+                let measured_rpm = fan_backend.read_fan_rpm(channel.pwm_index)?;
+                let controller = controllers.entry(channel.pwm_index).or_default();
+                let output = controller.step(
+                    closed_loop,
+                    target_rpm,
+                    measured_rpm,
+                    base_pwm,
+                    config::PwmBounds {
+                        min_pwm: channel.min_pwm,
+                        max_pwm: channel.max_pwm,
+                    },
+                    dt_secs,
+                );
+                if output.stalled {
+                    eprintln!(
+                        "Warning: pwm{} fan appears stalled (target {} RPM, 0 measured), kicking to full speed",
+                        channel.pwm_index, target_rpm
+                    );
+                }
+                output.pwm
+            } else {
+                base_pwm
+            };
+
+            fan_backend.set_pwm(channel.pwm_index, pwm)?;
+            last_poll.insert(channel.pwm_index, now);
         }
+        thread::sleep(Duration::from_secs(tick));
     }
-    Ok(())
 }
 
-fn set_pwm(pwm_index: u8, value: u8) -> io::Result<()> {
-    let path = find_hwmon_path_dynamic()?;
-    let enable_path = Path::new(&path).join(format!("pwm{}_enable", pwm_index));
-    let pwm_path = Path::new(&path).join(format!("pwm{}", pwm_index));
-    let max_path = Path::new(&path).join(format!("pwm{}_max", pwm_index));
-
-    fs::write(&enable_path, b"1")?;
-    fs::write(&pwm_path, format!("{}", value))?;
-
-    let max_val: u8 = if max_path.exists() {
-        fs::read_to_string(&max_path)?.trim().parse().unwrap_or(255)
-    } else {
-        255
-    };
-
-    let percent = (value as f32 / max_val as f32) * 100.0;
-    println!("Set pwm{} to {} (~{:.1}%)", pwm_index, value, percent);
-    Ok(())
+fn find_channel(config: &Config, pwm_index: u8) -> io::Result<&ChannelConfig> {
+    config
+        .channels
+        .iter()
+        .find(|c| c.pwm_index == pwm_index)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no [[channel]] configured for pwm{}", pwm_index),
+            )
+        })
 }
 
-fn set_mode(pwm_index: u8, mode: &str) -> io::Result<()> {
-    let path = find_hwmon_path_dynamic()?;
-    let enable_path = Path::new(&path).join(format!("pwm{}_enable", pwm_index));
-    let mode_val = match mode {
-        "manual" => "1",
-        "auto" => "2",
-        _ => {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "mode must be 'manual' or 'auto'",
-            ));
-        }
+fn set_cooling_state(
+    fan_backend: &dyn FanBackend,
+    config_path: Option<&str>,
+    pwm_index: u8,
+    index: usize,
+) -> io::Result<()> {
+    let config = match config_path {
+        Some(path) => Config::load_from(path)?,
+        None => Config::load()?,
     };
-    fs::write(enable_path, mode_val)?;
-    println!("Set pwm{} mode to {}", pwm_index, mode);
+    let channel = find_channel(&config, pwm_index)?;
+    let state = channel.cooling_states.get(index).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "pwm{} has {} cooling state(s), no state at index {}",
+                pwm_index,
+                channel.cooling_states.len(),
+                index
+            ),
+        )
+    })?;
+    fan_backend.set_pwm(pwm_index, state.pwm)?;
+    println!(
+        "Set pwm{} to cooling state {} ({}, pwm={})",
+        pwm_index, index, state.name, state.pwm
+    );
     Ok(())
 }
 
-fn temp_to_pwm(temp_c: f32) -> u8 {
-    match temp_c {
-        t if t <= 40.0 => 80,
-        t if t <= 50.0 => 128,
-        t if t <= 60.0 => 180,
-        _ => 255,
+fn list_cooling_states(
+    fan_backend: &dyn FanBackend,
+    config_path: Option<&str>,
+    pwm_index: u8,
+) -> io::Result<()> {
+    let config = match config_path {
+        Some(path) => Config::load_from(path)?,
+        None => Config::load()?,
+    };
+    let channel = find_channel(&config, pwm_index)?;
+    if channel.cooling_states.is_empty() {
+        println!("pwm{} has no configured cooling states", pwm_index);
+        return Ok(());
     }
-}
 
-fn run_daemon(pwm_index: u8) -> io::Result<()> {
-    println!("Starting fan control daemon on pwm{}", pwm_index);
-    loop {
-        let temp = read_cpu_temperature()?;
-        let pwm = temp_to_pwm(temp);
-        set_pwm(pwm_index, pwm)?;
-        thread::sleep(Duration::from_secs(5));
+    // The daemon's hysteresis state isn't available to a one-shot command,
+    // so "active" here is the table entry whose pwm matches what's
+    // currently on the wire, not a replay of the entry/exit history.
+    let current_pwm = fan_backend.read_pwm(pwm_index).ok();
+
+    for (i, state) in channel.cooling_states.iter().enumerate() {
+        let marker = if current_pwm == Some(state.pwm) {
+            " <- active"
+        } else {
+            ""
+        };
+        println!(
+            "[{}] {} pwm={} entry={:.1}°C exit={:.1}°C{}",
+            i, state.name, state.pwm, state.entry_temp_c, state.exit_temp_c, marker
+        );
     }
+    Ok(())
 }
 
 fn main() -> io::Result<()> {
-    if !check_module_loaded() {
-        println!("Warning: 'nct6775' kernel module is not loaded.");
-        println!("Run: sudo modprobe nct6775");
-    }
-
     let cli = Cli::parse();
 
     match &cli.command {
         Commands::Temp => {
-            let temp_c = read_cpu_temperature()?;
-            println!("Current CPU Temperature: {:.1} Â°C", temp_c);
+            let readings = sensors::enumerate_sensors()?;
+            let temp_c = sensors::resolve_source(sensors::DEFAULT_CPU_SOURCE, &readings)?;
+            println!("Current CPU Temperature: {:.1} °C", temp_c);
         }
         Commands::ListFans => {
-            list_fans()?;
+            let fan_backend = detect_backend(cli.backend.as_deref())?;
+            fan_backend.list_fans()?;
         }
         Commands::ListPwm => {
-            list_pwm()?;
+            let fan_backend = detect_backend(cli.backend.as_deref())?;
+            fan_backend.list_pwm()?;
         }
         Commands::SetPwm { pwm_index, value } => {
-            set_pwm(*pwm_index, *value)?;
+            let fan_backend = detect_backend(cli.backend.as_deref())?;
+            fan_backend.set_pwm(*pwm_index, *value)?;
+        }
+        Commands::SetMode {
+            pwm_index,
+            mode,
+            target_temp,
+            target_rpm,
+            crit_temp,
+        } => {
+            let fan_backend = detect_backend(cli.backend.as_deref())?;
+            fan_backend.set_mode(*pwm_index, mode, *target_temp, *target_rpm, *crit_temp)?;
+        }
+        Commands::SetOutputMode { pwm_index, mode } => {
+            let fan_backend = detect_backend(cli.backend.as_deref())?;
+            fan_backend.set_output_mode(*pwm_index, mode)?;
+        }
+        Commands::Daemon { config } => {
+            let fan_backend = detect_backend(cli.backend.as_deref())?;
+            run_daemon(config.as_deref(), fan_backend.as_ref())?;
         }
-        Commands::SetMode { pwm_index, mode } => {
-            set_mode(*pwm_index, mode)?;
+        Commands::SetCoolingState {
+            pwm_index,
+            index,
+            config,
+        } => {
+            let fan_backend = detect_backend(cli.backend.as_deref())?;
+            set_cooling_state(fan_backend.as_ref(), config.as_deref(), *pwm_index, *index)?;
         }
-        Commands::Daemon { pwm_index } => {
-            run_daemon(*pwm_index)?;
+        Commands::ListCoolingStates { pwm_index, config } => {
+            let fan_backend = detect_backend(cli.backend.as_deref())?;
+            list_cooling_states(fan_backend.as_ref(), config.as_deref(), *pwm_index)?;
         }
     }
 
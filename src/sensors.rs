@@ -0,0 +1,100 @@
+/*
+ * Multi-sensor temperature fusion
+ *
+ * Scans /sys/class/hwmon for every tempN_input exposed by any chip (not just
+ * k10temp), so a fan curve can react to VRM/coolant sensors or take the
+ * hottest of several inputs.
+ */
+
+use std::fs;
+use std::io;
+
+const HWMON_PATH: &str = "/sys/class/hwmon";
+
+/// The default temperature source for commands that just want "the" CPU
+/// temperature rather than a specific named sensor.
+pub const DEFAULT_CPU_SOURCE: &str = "k10temp/Tctl";
+
+/// A single `tempN_input` reading, tagged with the chip it came from and its
+/// label (or "tempN" when the chip doesn't export one).
+#[derive(Debug, Clone)]
+pub struct SensorReading {
+    pub chip: String,
+    pub label: String,
+    pub temp_c: f32,
+}
+
+impl SensorReading {
+    /// The name a fan curve's `source` field refers to this reading by,
+    /// e.g. "k10temp/Tctl".
+    pub fn source_name(&self) -> String {
+        format!("{}/{}", self.chip, self.label)
+    }
+}
+
+/// Scan every hwmon device for `tempN_input` files, pairing each with its
+/// `tempN_label` when present.
+pub fn enumerate_sensors() -> io::Result<Vec<SensorReading>> {
+    let mut readings = Vec::new();
+
+    for entry in fs::read_dir(HWMON_PATH)? {
+        let entry = entry?;
+        let dir = entry.path();
+        let chip = match fs::read_to_string(dir.join("name")) {
+            Ok(name) => name.trim().to_string(),
+            Err(_) => continue,
+        };
+
+        for i in 1..=10 {
+            let input_path = dir.join(format!("temp{}_input", i));
+            if !input_path.exists() {
+                continue;
+            }
+            let raw = match fs::read_to_string(&input_path) {
+                Ok(raw) => raw,
+                Err(_) => continue,
+            };
+            let milli_degrees: i32 = match raw.trim().parse() {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+
+            let label_path = dir.join(format!("temp{}_label", i));
+            let label = fs::read_to_string(&label_path)
+                .map(|s| s.trim().to_string())
+                .unwrap_or_else(|_| format!("temp{}", i));
+
+            readings.push(SensorReading {
+                chip: chip.clone(),
+                label,
+                temp_c: milli_degrees as f32 / 1000.0,
+            });
+        }
+    }
+
+    Ok(readings)
+}
+
+/// Resolve a fan curve's `source` string against the current readings.
+/// "max" is a synthetic source meaning the hottest of all enumerated
+/// sensors; anything else is matched as "chip/label".
+pub fn resolve_source(source: &str, readings: &[SensorReading]) -> io::Result<f32> {
+    if source == "max" {
+        return readings
+            .iter()
+            .map(|r| r.temp_c)
+            .fold(None, |acc, t| Some(acc.map_or(t, |a: f32| a.max(t))))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no temperature sensors found"));
+    }
+
+    readings
+        .iter()
+        .find(|r| r.source_name() == source)
+        .map(|r| r.temp_c)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("temperature source '{}' not found", source),
+            )
+        })
+}